@@ -1,6 +1,10 @@
 use clap::Parser;
 use std::path::PathBuf;
 
+use crate::color::ColorSpace;
+use crate::output::OutputFormat;
+use crate::video::Decoder;
+
 /// Extracts the color palette from a video
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -21,10 +25,23 @@ pub struct Args {
     #[arg(short, long, default_value = "12")]
     pub resize_height: i32,
 
+    /// Only sample every Nth frame (the OpenCV decoder seeks to each sampled frame; the ffmpeg
+    /// decoder drops the frames in between with its `fps` filter)
+    #[arg(long, default_value = "1")]
+    pub frame_step: u64,
+
+    /// Which backend to decode video frames with
+    #[arg(long, value_enum, default_value = "opencv")]
+    pub decoder: Decoder,
+
     /// Number of color clusters to create
     #[arg(short, long, default_value = "5")]
     pub color_clusters: usize,
 
+    /// Which color space to cluster in
+    #[arg(long, value_enum, default_value = "rgb")]
+    pub color_space: ColorSpace,
+
     /// Start time of the video to extract colors (format: HH:MM:SS)
     #[arg(long, value_parser = parse_duration)]
     pub start: Option<std::time::Duration>,
@@ -32,6 +49,24 @@ pub struct Args {
     /// End time of the video to extract colors (format: HH:MM:SS)
     #[arg(long, value_parser = parse_duration)]
     pub end: Option<std::time::Duration>,
+
+    /// Enable scene-cut detection and emit a per-scene palette plus a "movie barcode" PNG
+    /// instead of a single whole-video palette. The value is the normalized histogram
+    /// intersection distance between consecutive sampled frames above which a cut is flagged.
+    #[arg(long)]
+    pub scene_threshold: Option<f32>,
+
+    /// Where to write the movie-barcode PNG when `--scene-threshold` is set
+    #[arg(long, default_value = "barcode.png")]
+    pub barcode_output: PathBuf,
+
+    /// Output format for the generated palette
+    #[arg(long, value_enum, default_value = "terminal")]
+    pub format: OutputFormat,
+
+    /// Path to write the output to; ignored when `--format` is `terminal`
+    #[arg(long, default_value = "palette")]
+    pub output: PathBuf,
 }
 
 fn parse_duration(arg: &str) -> Result<std::time::Duration, std::num::ParseIntError> {
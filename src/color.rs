@@ -2,14 +2,26 @@ use std::collections::HashMap;
 use std::fmt::Write;
 use std::hash::{Hash, Hasher};
 
+use clap::ValueEnum;
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use kmeans::{KMeans, KMeansConfig};
 use opencv::prelude::*;
-use opencv::{core, imgproc};
-use palette::{Hsv, IntoColor, Srgb};
+use palette::white_point::D65;
+use palette::{Hsv, IntoColor, Lab, Srgb};
+use rand::Rng;
 
 use crate::video::VideoFrameIterator;
 
+/// Which color space `create_color_clusters` measures distance in.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ColorSpace {
+    /// Raw sRGB values with Euclidean distance. Fast, but groups colors in a way
+    /// that doesn't match human perception.
+    Rgb,
+    /// CIE L*a*b* with CIEDE2000 distance, matching perceived color difference much more
+    /// closely at the cost of a slower distance computation in the same Lloyd's loop.
+    Lab,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Color(pub Srgb<u8>);
 
@@ -33,17 +45,20 @@ impl Eq for Color {}
 pub struct ColorCluster {
     pub centroid: Color,
     pub assignments: Vec<(Color, usize)>,
+    /// Mean CIEDE2000 distance from the centroid to each assigned color, weighted by pixel
+    /// count. `None` when clustering was done in `ColorSpace::Rgb`, which has no perceptual
+    /// distance to report.
+    pub delta_e_spread: Option<f64>,
 }
 
 pub fn extract_color_ranking_from_video(
     frame_iterator: VideoFrameIterator,
-    resize_height: i32,
     saturation_threshold: f32,
     luminance_threshold: f32,
 ) -> Result<Vec<(Color, usize)>, Box<dyn std::error::Error>> {
     let mut color_counts: HashMap<Color, usize> = HashMap::new();
 
-    let pb_length = frame_iterator.end_frame - frame_iterator.frame_number;
+    let pb_length = frame_iterator.estimated_remaining();
     let pb = ProgressBar::new(pb_length);
     pb.set_style(
         ProgressStyle::with_template(
@@ -57,12 +72,7 @@ pub fn extract_color_ranking_from_video(
     );
 
     for (frame_number, frame) in frame_iterator.enumerate() {
-        let colors = extract_colors_from_frame(
-            &frame,
-            resize_height,
-            saturation_threshold,
-            luminance_threshold,
-        );
+        let colors = extract_colors_from_frame(&frame, saturation_threshold, luminance_threshold);
         for color in colors {
             *color_counts.entry(color).or_insert(0) += 1;
         }
@@ -78,30 +88,17 @@ pub fn extract_color_ranking_from_video(
     Ok(color_ranking)
 }
 
+/// Extract the filtered, sampled colors from an already-resized RGB frame.
+///
+/// `frame` is expected to have already been resized to the common sampling height and
+/// converted to RGB by the `VideoSource` that produced it.
 pub fn extract_colors_from_frame(
     frame: &Mat,
-    resize_height: i32,
     saturation_threshold: f32,
     luminance_threshold: f32,
 ) -> Vec<Color> {
-    let aspect_ratio = frame.cols() as f64 / frame.rows() as f64;
-    let new_width = (resize_height as f64 * aspect_ratio).round() as i32;
-
-    let mut resized_frame = Mat::default();
-    imgproc::resize(
-        &frame,
-        &mut resized_frame,
-        core::Size::new(new_width, resize_height),
-        0.,
-        0.,
-        imgproc::INTER_AREA,
-    )
-    .unwrap();
-    let mut rgb_frame = Mat::default();
-    imgproc::cvt_color(&resized_frame, &mut rgb_frame, imgproc::COLOR_BGR2RGB, 3).unwrap();
-
-    let data_start = rgb_frame.datastart();
-    let data_end = rgb_frame.dataend();
+    let data_start = frame.datastart();
+    let data_end = frame.dataend();
     let data_slice =
         unsafe { std::slice::from_raw_parts(data_start, data_end as usize - data_start as usize) };
 
@@ -134,61 +131,429 @@ pub fn extract_colors_from_frame(
 pub fn create_color_clusters(
     color_ranking: &[(Color, usize)],
     num_clusters: usize,
+    color_space: ColorSpace,
 ) -> Vec<ColorCluster> {
-    // Convert colors to f64 and create a Vec of tuples containing the color data and count
-    let data_and_counts: Vec<(Vec<f64>, usize)> = color_ranking
+    if color_ranking.is_empty() {
+        // Nothing survived the saturation/luminance filter (fade-to-black, a solid title
+        // card, ...) — there's nothing to cluster, so report no clusters rather than
+        // indexing into empty point/weight vectors below.
+        return Vec::new();
+    }
+
+    match color_space {
+        ColorSpace::Rgb => create_color_clusters_rgb(color_ranking, num_clusters),
+        ColorSpace::Lab => create_color_clusters_lab(color_ranking, num_clusters),
+    }
+}
+
+fn euclidean_distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Weighted k-means++ seeding: the first centroid is picked uniformly at random weighted by
+/// pixel count, and every subsequent centroid is picked with probability proportional to
+/// `weight * distance(point, nearest already-chosen centroid)^2` — points far from existing
+/// centroids (and with more pixels behind them) are more likely to be chosen, spreading the
+/// initial centroids out instead of clumping them.
+fn seed_centroids(
+    points: &[[f64; 3]],
+    weights: &[u64],
+    num_clusters: usize,
+    distance: &impl Fn([f64; 3], [f64; 3]) -> f64,
+) -> Vec<[f64; 3]> {
+    let mut rng = rand::thread_rng();
+    let mut centroids = Vec::with_capacity(num_clusters);
+
+    let total_weight: u64 = weights.iter().sum::<u64>().max(1);
+    let mut target = rng.gen_range(0..total_weight);
+    let first = weights
         .iter()
-        .map(|(color, count)| {
-            let color_f = color.0.into_format::<f64>();
-            (vec![color_f.red, color_f.green, color_f.blue], *count)
+        .position(|&w| {
+            if target < w {
+                true
+            } else {
+                target -= w;
+                false
+            }
         })
-        .collect();
+        .unwrap_or(0);
+    centroids.push(points[first]);
 
-    // Calculate the total number of samples and the sample dimensions
-    let total_samples: usize = data_and_counts.iter().map(|(_, count)| count).sum();
-    let sample_dims = 3;
+    while centroids.len() < num_clusters {
+        let scores: Vec<f64> = points
+            .iter()
+            .zip(weights.iter())
+            .map(|(point, weight)| {
+                let nearest_dist = centroids
+                    .iter()
+                    .map(|centroid| distance(*point, *centroid))
+                    .fold(f64::INFINITY, f64::min);
+                *weight as f64 * nearest_dist * nearest_dist
+            })
+            .collect();
 
-    // Create a flat Vec of f64 data for KMeans clustering
-    let mut data: Vec<f64> = Vec::with_capacity(total_samples * sample_dims);
-    for (color_data, count) in data_and_counts {
-        for _ in 0..count {
-            // repeating this for each count gives more weight to colors with higher counts
-            data.extend(color_data.clone());
+        let total: f64 = scores.iter().sum();
+        if total <= 0.0 {
+            // Every remaining point already sits on a chosen centroid; any point is as good
+            // as any other as a (duplicate, but harmless) extra seed.
+            centroids.push(points[0]);
+            continue;
         }
+
+        let mut target = rng.gen_range(0.0..total);
+        let chosen = scores
+            .iter()
+            .position(|score| {
+                if target < *score {
+                    true
+                } else {
+                    target -= score;
+                    false
+                }
+            })
+            .unwrap_or(points.len() - 1);
+        centroids.push(points[chosen]);
     }
 
-    // Perform KMeans clustering
-    let kmeans = KMeans::new(data, total_samples, sample_dims);
-    let result = kmeans.kmeans_lloyd(
-        num_clusters,
-        100,
-        KMeans::init_kmeanplusplus,
-        &KMeansConfig::default(),
-    );
+    centroids
+}
 
-    // Assign colors to their respective clusters based on the clustering result
-    let mut assignments: Vec<Vec<(Color, usize)>> = vec![vec![]; num_clusters];
-    let mut assignment_iter = result.assignments.into_iter();
-    for (color, count) in color_ranking {
-        if let Some(cluster_idx) = assignment_iter.next() {
-            assignments[cluster_idx].push((*color, *count));
+/// Weighted Lloyd's k-means over one row per unique sample (rather than one row per pixel):
+/// each `points[i]` carries `weights[i]` worth of pixel count, so memory and clustering time
+/// both scale with the number of distinct colors instead of total pixels sampled. `distance`
+/// is the dissimilarity metric used for both k-means++ seeding and nearest-centroid assignment,
+/// letting this same loop back both the RGB/Euclidean and Lab/CIEDE2000 color spaces.
+///
+/// Returns the final centroids and each point's assigned cluster index.
+fn weighted_kmeans(
+    points: &[[f64; 3]],
+    weights: &[u64],
+    num_clusters: usize,
+    max_iterations: usize,
+    distance: impl Fn([f64; 3], [f64; 3]) -> f64,
+) -> (Vec<[f64; 3]>, Vec<usize>) {
+    let mut centroids = seed_centroids(points, weights, num_clusters, &distance);
+    let mut assignment = vec![0usize; points.len()];
+
+    for _ in 0..max_iterations {
+        for (i, point) in points.iter().enumerate() {
+            assignment[i] = centroids
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    distance(*point, **a)
+                        .partial_cmp(&distance(*point, **b))
+                        .unwrap()
+                })
+                .map(|(idx, _)| idx)
+                .unwrap();
+        }
+
+        let mut sums = vec![[0.0; 3]; num_clusters];
+        let mut cluster_weights = vec![0u64; num_clusters];
+        for (i, point) in points.iter().enumerate() {
+            let cluster = assignment[i];
+            let weight = weights[i] as f64;
+            sums[cluster][0] += point[0] * weight;
+            sums[cluster][1] += point[1] * weight;
+            sums[cluster][2] += point[2] * weight;
+            cluster_weights[cluster] += weights[i];
         }
+
+        let mut converged = true;
+        for cluster in 0..num_clusters {
+            if cluster_weights[cluster] == 0 {
+                continue;
+            }
+            let weight = cluster_weights[cluster] as f64;
+            let new_centroid = [
+                sums[cluster][0] / weight,
+                sums[cluster][1] / weight,
+                sums[cluster][2] / weight,
+            ];
+            if distance(new_centroid, centroids[cluster]) > 1e-6 {
+                converged = false;
+            }
+            centroids[cluster] = new_centroid;
+        }
+
+        if converged {
+            break;
+        }
+    }
+
+    (centroids, assignment)
+}
+
+fn create_color_clusters_rgb(
+    color_ranking: &[(Color, usize)],
+    num_clusters: usize,
+) -> Vec<ColorCluster> {
+    let num_clusters = num_clusters.min(color_ranking.len()).max(1);
+
+    let points: Vec<[f64; 3]> = color_ranking
+        .iter()
+        .map(|(color, _)| {
+            let c = color.0.into_format::<f64>();
+            [c.red, c.green, c.blue]
+        })
+        .collect();
+    let weights: Vec<u64> = color_ranking.iter().map(|(_, count)| *count as u64).collect();
+
+    let (centroids, assignment) =
+        weighted_kmeans(&points, &weights, num_clusters, 100, euclidean_distance);
+
+    let mut assignments: Vec<Vec<(Color, usize)>> = vec![vec![]; num_clusters];
+    for (idx, (color, count)) in color_ranking.iter().enumerate() {
+        assignments[assignment[idx]].push((*color, *count));
     }
 
-    // Convert centroids back to Color and create ColorCluster structs
-    let centroids = result.centroids.chunks(sample_dims).map(|chunk| {
-        let r = (chunk[0] * 255.0).clamp(0.0, 255.0) as u8;
-        let g = (chunk[1] * 255.0).clamp(0.0, 255.0) as u8;
-        let b = (chunk[2] * 255.0).clamp(0.0, 255.0) as u8;
-        Color(Srgb::new(r, g, b))
-    });
+    centroids
+        .into_iter()
+        .enumerate()
+        .map(|(cluster, centroid)| {
+            let r = (centroid[0] * 255.0).clamp(0.0, 255.0) as u8;
+            let g = (centroid[1] * 255.0).clamp(0.0, 255.0) as u8;
+            let b = (centroid[2] * 255.0).clamp(0.0, 255.0) as u8;
+
+            ColorCluster {
+                centroid: Color(Srgb::new(r, g, b)),
+                assignments: std::mem::take(&mut assignments[cluster]),
+                delta_e_spread: None,
+            }
+        })
+        .collect()
+}
+
+type LabD65 = Lab<D65, f64>;
+
+/// The CIEDE2000 color difference between two CIE L*a*b* colors (Sharma, Wu & Dalal, 2005).
+/// Lower is more perceptually similar; `0.0` is identical.
+fn ciede2000(lab1: LabD65, lab2: LabD65) -> f64 {
+    let (l1, a1, b1) = (lab1.l, lab1.a, lab1.b);
+    let (l2, a2, b2) = (lab2.l, lab2.a, lab2.b);
+
+    let c1 = (a1 * a1 + b1 * b1).sqrt();
+    let c2 = (a2 * a2 + b2 * b2).sqrt();
+    let c_bar = (c1 + c2) / 2.0;
+
+    let c_bar7 = c_bar.powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + 25f64.powi(7))).sqrt());
+
+    let a1_prime = a1 * (1.0 + g);
+    let a2_prime = a2 * (1.0 + g);
+
+    let c1_prime = (a1_prime * a1_prime + b1 * b1).sqrt();
+    let c2_prime = (a2_prime * a2_prime + b2 * b2).sqrt();
+
+    let h1_prime = if b1 == 0.0 && a1_prime == 0.0 {
+        0.0
+    } else {
+        b1.atan2(a1_prime).to_degrees().rem_euclid(360.0)
+    };
+    let h2_prime = if b2 == 0.0 && a2_prime == 0.0 {
+        0.0
+    } else {
+        b2.atan2(a2_prime).to_degrees().rem_euclid(360.0)
+    };
+
+    let delta_l_prime = l2 - l1;
+    let delta_c_prime = c2_prime - c1_prime;
+
+    let h_diff = h2_prime - h1_prime;
+    let delta_h_prime = if c1_prime * c2_prime == 0.0 {
+        0.0
+    } else if h_diff.abs() <= 180.0 {
+        h_diff
+    } else if h_diff > 180.0 {
+        h_diff - 360.0
+    } else {
+        h_diff + 360.0
+    };
+    let delta_big_h_prime =
+        2.0 * (c1_prime * c2_prime).sqrt() * (delta_h_prime.to_radians() / 2.0).sin();
+
+    let l_bar_prime = (l1 + l2) / 2.0;
+    let c_bar_prime = (c1_prime + c2_prime) / 2.0;
+
+    let h_sum = h1_prime + h2_prime;
+    let h_bar_prime = if c1_prime * c2_prime == 0.0 {
+        h_sum
+    } else if (h1_prime - h2_prime).abs() <= 180.0 {
+        h_sum / 2.0
+    } else if h_sum < 360.0 {
+        (h_sum + 360.0) / 2.0
+    } else {
+        (h_sum - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar_prime - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar_prime).to_radians().cos()
+        + 0.32 * (3.0 * h_bar_prime + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar_prime - 63.0).to_radians().cos();
+
+    let delta_theta = 30.0 * (-((h_bar_prime - 275.0) / 25.0).powi(2)).exp();
+    let c_bar_prime7 = c_bar_prime.powi(7);
+    let r_c = 2.0 * (c_bar_prime7 / (c_bar_prime7 + 25f64.powi(7))).sqrt();
+    let r_t = -r_c * (2.0 * delta_theta.to_radians()).sin();
+
+    let s_l = 1.0 + (0.015 * (l_bar_prime - 50.0).powi(2)) / (20.0 + (l_bar_prime - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_prime;
+    let s_h = 1.0 + 0.015 * c_bar_prime * t;
+
+    let term_l = delta_l_prime / s_l;
+    let term_c = delta_c_prime / s_c;
+    let term_h = delta_big_h_prime / s_h;
+
+    (term_l.powi(2) + term_c.powi(2) + term_h.powi(2) + r_t * term_c * term_h).sqrt()
+}
+
+fn lab_distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    ciede2000(LabD65::new(a[0], a[1], a[2]), LabD65::new(b[0], b[1], b[2]))
+}
+
+/// Clusters in CIE L*a*b* space using the same weighted Lloyd's loop as the RGB path, but with
+/// CIEDE2000 standing in for Euclidean distance.
+fn create_color_clusters_lab(
+    color_ranking: &[(Color, usize)],
+    num_clusters: usize,
+) -> Vec<ColorCluster> {
+    let num_clusters = num_clusters.min(color_ranking.len()).max(1);
+
+    let points: Vec<[f64; 3]> = color_ranking
+        .iter()
+        .map(|(color, _)| {
+            let lab: LabD65 = color.0.into_format::<f64>().into_color();
+            [lab.l, lab.a, lab.b]
+        })
+        .collect();
+    let weights: Vec<u64> = color_ranking.iter().map(|(_, count)| *count as u64).collect();
+
+    let (centroids, assignment) = weighted_kmeans(&points, &weights, num_clusters, 100, lab_distance);
+
+    let mut assignments: Vec<Vec<(Color, usize)>> = vec![vec![]; num_clusters];
+    let mut delta_e_sum: Vec<f64> = vec![0.0; num_clusters];
+    let mut delta_e_weight: Vec<f64> = vec![0.0; num_clusters];
+    for (idx, (color, count)) in color_ranking.iter().enumerate() {
+        let cluster = assignment[idx];
+        assignments[cluster].push((*color, *count));
+        delta_e_sum[cluster] += lab_distance(points[idx], centroids[cluster]) * (*count as f64);
+        delta_e_weight[cluster] += *count as f64;
+    }
 
     centroids
         .into_iter()
-        .zip(assignments.into_iter())
-        .map(|(centroid, assignments)| ColorCluster {
-            centroid,
-            assignments,
+        .enumerate()
+        .map(|(cluster, centroid)| {
+            let lab = LabD65::new(centroid[0], centroid[1], centroid[2]);
+            let srgb: Srgb<f64> = lab.into_color();
+            let r = (srgb.red.clamp(0.0, 1.0) * 255.0).round() as u8;
+            let g = (srgb.green.clamp(0.0, 1.0) * 255.0).round() as u8;
+            let b = (srgb.blue.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+            let delta_e_spread = if delta_e_weight[cluster] > 0.0 {
+                Some(delta_e_sum[cluster] / delta_e_weight[cluster])
+            } else {
+                None
+            };
+
+            ColorCluster {
+                centroid: Color(Srgb::new(r, g, b)),
+                assignments: std::mem::take(&mut assignments[cluster]),
+                delta_e_spread,
+            }
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reference ΔE00 pairs from Sharma, Wu & Dalal (2005), "The CIEDE2000 Color-Difference
+    /// Formula: Implementation Notes, Supplementary Test Data, and Mathematical Observations",
+    /// Table 1. These exercise the hue-wrap, grey-axis, and chroma-near-zero edge cases that a
+    /// sign or rounding slip in a hand-rolled implementation would otherwise miss silently.
+    #[test]
+    fn ciede2000_matches_published_reference_values() {
+        let cases = [
+            ((50.0000, 2.6772, -79.7751), (50.0000, 0.0000, -82.7485), 2.0425),
+            ((50.0000, -1.3802, -84.2814), (50.0000, 0.0000, -82.7485), 1.0000),
+            ((50.0000, 0.0000, 0.0000), (50.0000, -1.0000, 2.0000), 2.3669),
+            ((50.0000, 2.4900, -0.0010), (50.0000, -2.4900, 0.0009), 7.1792),
+        ];
+
+        for ((l1, a1, b1), (l2, a2, b2), expected) in cases {
+            let delta_e = ciede2000(LabD65::new(l1, a1, b1), LabD65::new(l2, a2, b2));
+            assert!(
+                (delta_e - expected).abs() < 0.01,
+                "expected {}, got {}",
+                expected,
+                delta_e
+            );
+        }
+    }
+
+    #[test]
+    fn ciede2000_is_zero_for_identical_colors() {
+        let lab = LabD65::new(61.2, 12.4, -33.8);
+        assert_eq!(ciede2000(lab, lab), 0.0);
+    }
+
+    #[test]
+    fn seed_centroids_with_a_single_point_returns_that_point() {
+        let points = [[1.0, 2.0, 3.0]];
+        let weights = [5u64];
+        let centroids = seed_centroids(&points, &weights, 1, &euclidean_distance);
+        assert_eq!(centroids, vec![[1.0, 2.0, 3.0]]);
+    }
+
+    #[test]
+    fn weighted_kmeans_recovers_well_separated_clusters() {
+        use std::collections::HashSet;
+
+        // Three tight, widely-separated clumps of points; k-means++ seeding picks one seed per
+        // clump on all but a tiny fraction of random draws (a second seed in an already-seeded
+        // clump has odds far below one in ten thousand), so this converges to the same
+        // partition on practically every run.
+        let points = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [100.0, 100.0, 100.0],
+            [101.0, 100.0, 100.0],
+            [100.0, 101.0, 100.0],
+            [101.0, 101.0, 100.0],
+            [200.0, 0.0, 200.0],
+            [201.0, 0.0, 200.0],
+            [200.0, 1.0, 200.0],
+            [201.0, 1.0, 200.0],
+        ];
+        let weights = vec![1u64; points.len()];
+
+        let (centroids, assignment) = weighted_kmeans(&points, &weights, 3, 100, euclidean_distance);
+
+        assert_eq!(centroids.len(), 3);
+        // Every point within the same clump must land in the same cluster...
+        assert_eq!(assignment[0..4].iter().collect::<HashSet<_>>().len(), 1);
+        assert_eq!(assignment[4..8].iter().collect::<HashSet<_>>().len(), 1);
+        assert_eq!(assignment[8..12].iter().collect::<HashSet<_>>().len(), 1);
+        // ...and each clump must land in a different cluster from the others.
+        let clump_clusters: HashSet<usize> =
+            [assignment[0], assignment[4], assignment[8]].into_iter().collect();
+        assert_eq!(clump_clusters.len(), 3);
+    }
+
+    #[test]
+    fn weighted_kmeans_weights_pull_the_centroid_toward_the_heavier_point() {
+        let points = vec![[0.0, 0.0, 0.0], [10.0, 0.0, 0.0]];
+        let weights = vec![1u64, 9u64];
+
+        let (centroids, _) = weighted_kmeans(&points, &weights, 1, 100, euclidean_distance);
+
+        assert_eq!(centroids.len(), 1);
+        // The weighted mean is 9.0, not the unweighted midpoint of 5.0.
+        assert!((centroids[0][0] - 9.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,40 @@
+use image::{Rgb, RgbImage};
+use palette::Srgb;
+
+use crate::color::Color;
+
+/// A fixed width for rendered color strips (movie barcodes, palette swatches): wide enough to
+/// show fine-grained proportions without the output PNG becoming unwieldy.
+const STRIP_WIDTH: u32 = 1000;
+
+/// Render a horizontal strip image where each entry occupies a column whose width is
+/// proportional to its `weight` (e.g. a scene's frame span, or a cluster's assigned pixel
+/// count). Shared by the movie-barcode timeline and the palette swatch-strip export.
+pub fn render_color_strip(entries: &[(Color, u64)], height: u32) -> RgbImage {
+    let mut image = RgbImage::new(STRIP_WIDTH, height);
+    if entries.is_empty() {
+        return image;
+    }
+
+    let total_weight: u64 = entries.iter().map(|(_, weight)| weight).sum::<u64>().max(1);
+
+    let mut x = 0u32;
+    for (color, weight) in entries {
+        if x >= STRIP_WIDTH {
+            break;
+        }
+
+        let column_width = ((*weight as f64 / total_weight as f64) * STRIP_WIDTH as f64).round() as u32;
+        let column_width = column_width.clamp(1, STRIP_WIDTH - x);
+
+        let Srgb { red, green, blue, .. } = color.0;
+        for px in x..(x + column_width) {
+            for y in 0..height {
+                image.put_pixel(px, y, Rgb([red, green, blue]));
+            }
+        }
+        x += column_width;
+    }
+
+    image
+}
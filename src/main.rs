@@ -1,8 +1,11 @@
 mod args;
 mod color;
+mod image_output;
+mod output;
+mod scenes;
 mod video;
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
 use colored::*;
@@ -10,6 +13,8 @@ use palette::Srgb;
 
 use args::Args;
 use color::{create_color_clusters, extract_color_ranking_from_video, Color, ColorCluster};
+use output::{write_palette, OutputFormat};
+use scenes::{build_scene_palettes, detect_scenes, write_scene_barcode, ScenePalette};
 use video::VideoFrameIterator;
 
 fn main() {
@@ -21,7 +26,14 @@ fn main() {
         std::process::exit(1);
     }
 
-    let video_frame_iter = match VideoFrameIterator::new(video_file, args.start, args.end) {
+    let video_frame_iter = match VideoFrameIterator::new(
+        video_file,
+        args.start,
+        args.end,
+        args.frame_step,
+        args.resize_height,
+        args.decoder,
+    ) {
         Ok(iter) => iter,
         Err(e) => {
             eprintln!("Error opening video: {}", e);
@@ -29,9 +41,62 @@ fn main() {
         }
     };
 
+    if let Some(scene_threshold) = args.scene_threshold {
+        let scenes = match detect_scenes(
+            video_frame_iter,
+            args.saturation,
+            args.luminance,
+            scene_threshold as f64,
+        ) {
+            Ok(scenes) => scenes,
+            Err(e) => {
+                eprintln!("Error segmenting scenes: {}", e);
+                std::process::exit(1);
+            }
+        };
+
+        let scene_palettes = build_scene_palettes(&scenes, args.color_clusters, args.color_space);
+
+        if let Err(e) = write_scene_barcode(&scene_palettes, &args.barcode_output) {
+            eprintln!("Error writing movie barcode: {}", e);
+            std::process::exit(1);
+        }
+
+        print_scene_palettes(&scene_palettes);
+        println!("Wrote movie barcode to {}", args.barcode_output.display());
+
+        if !matches!(args.format, OutputFormat::Terminal) {
+            for (idx, palette) in scene_palettes.iter().enumerate() {
+                // A cluster's own assignments already are that scene's full color ranking, so
+                // flatten and re-sort them rather than threading the raw per-scene ranking
+                // through `ScenePalette` just for this.
+                let mut color_ranking: Vec<(Color, usize)> = palette
+                    .clusters
+                    .iter()
+                    .flat_map(|cluster| cluster.assignments.iter().copied())
+                    .collect();
+                color_ranking.sort_by(|a, b| b.1.cmp(&a.1));
+
+                let scene_output = scene_output_path(&args.output, idx + 1);
+                if let Err(e) = write_palette(
+                    args.format,
+                    &scene_output,
+                    color_ranking.as_slice(),
+                    palette.clusters.as_slice(),
+                    10,
+                ) {
+                    eprintln!("Error writing output for scene {}: {}", idx + 1, e);
+                    std::process::exit(1);
+                }
+                println!("Wrote scene {} palette to {}", idx + 1, scene_output.display());
+            }
+        }
+
+        return;
+    }
+
     let color_ranking = match extract_color_ranking_from_video(
         video_frame_iter,
-        args.resize_height,
         args.saturation,
         args.luminance,
     ) {
@@ -44,9 +109,35 @@ fn main() {
 
     print_color_palette(color_ranking.as_slice());
 
-    let color_clusters = create_color_clusters(color_ranking.as_slice(), args.color_clusters);
+    let color_clusters =
+        create_color_clusters(color_ranking.as_slice(), args.color_clusters, args.color_space);
 
-    print_color_clusters(color_clusters.as_slice())
+    print_color_clusters(color_clusters.as_slice());
+
+    if !matches!(args.format, OutputFormat::Terminal) {
+        if let Err(e) = write_palette(
+            args.format,
+            &args.output,
+            color_ranking.as_slice(),
+            color_clusters.as_slice(),
+            10,
+        ) {
+            eprintln!("Error writing output: {}", e);
+            std::process::exit(1);
+        }
+        println!("Wrote palette to {}", args.output.display());
+    }
+}
+
+/// Derive a per-scene output path from the `--output` base path by inserting `-scene-{n}`
+/// before the extension (or at the end, if there is none).
+fn scene_output_path(base: &Path, scene_number: usize) -> PathBuf {
+    let stem = base.file_stem().and_then(|s| s.to_str()).unwrap_or("palette");
+    let file_name = match base.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}-scene-{}.{}", stem, scene_number, ext),
+        None => format!("{}-scene-{}", stem, scene_number),
+    };
+    base.with_file_name(file_name)
 }
 
 fn print_color_palette(palette: &[(Color, usize)]) {
@@ -77,6 +168,28 @@ fn print_color_clusters(clusters: &[ColorCluster]) {
         } = cluster.centroid.0;
         let color_code = format!("{:02X}{:02X}{:02X}", red, green, blue);
         let color_block = "  ".on_truecolor(red, green, blue);
-        println!("{}. Centroid: #{} {}", idx + 1, color_code, color_block);
+        match cluster.delta_e_spread {
+            Some(delta_e) => println!(
+                "{}. Centroid: #{} {} (mean \u{394}E: {:.2})",
+                idx + 1,
+                color_code,
+                color_block,
+                delta_e
+            ),
+            None => println!("{}. Centroid: #{} {}", idx + 1, color_code, color_block),
+        }
+    }
+}
+
+fn print_scene_palettes(scene_palettes: &[ScenePalette]) {
+    println!("Detected {} scenes:", scene_palettes.len());
+    for (idx, palette) in scene_palettes.iter().enumerate() {
+        println!(
+            "Scene {} (sampled frames {}-{}):",
+            idx + 1,
+            palette.start_frame,
+            palette.end_frame
+        );
+        print_color_clusters(palette.clusters.as_slice());
     }
 }
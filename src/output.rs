@@ -0,0 +1,148 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+use palette::Srgb;
+use serde::Serialize;
+
+use crate::color::{Color, ColorCluster};
+use crate::image_output::render_color_strip;
+
+/// How the generated palette should be written out.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum OutputFormat {
+    /// Print truecolor blocks to the terminal (the default, unchanged from before).
+    Terminal,
+    /// The top-ranked colors and every cluster, as JSON.
+    Json,
+    /// A GIMP-compatible `.gpl` palette of the cluster centroids.
+    Gpl,
+    /// CSS custom properties, one per cluster centroid.
+    Css,
+    /// A horizontal swatch strip PNG, one segment per cluster sized by its pixel count.
+    Png,
+}
+
+fn hex(color: &Color) -> String {
+    let Srgb { red, green, blue, .. } = color.0;
+    format!("#{:02X}{:02X}{:02X}", red, green, blue)
+}
+
+#[derive(Serialize)]
+struct RankedColorExport {
+    hex: String,
+    count: usize,
+}
+
+#[derive(Serialize)]
+struct ClusterExport {
+    centroid_hex: String,
+    colors: Vec<RankedColorExport>,
+}
+
+#[derive(Serialize)]
+struct PaletteExport {
+    top_colors: Vec<RankedColorExport>,
+    clusters: Vec<ClusterExport>,
+}
+
+fn write_json(
+    path: &Path,
+    color_ranking: &[(Color, usize)],
+    clusters: &[ColorCluster],
+    top_n: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let export = PaletteExport {
+        top_colors: color_ranking
+            .iter()
+            .take(top_n)
+            .map(|(color, count)| RankedColorExport {
+                hex: hex(color),
+                count: *count,
+            })
+            .collect(),
+        clusters: clusters
+            .iter()
+            .map(|cluster| ClusterExport {
+                centroid_hex: hex(&cluster.centroid),
+                colors: cluster
+                    .assignments
+                    .iter()
+                    .map(|(color, count)| RankedColorExport {
+                        hex: hex(color),
+                        count: *count,
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+
+    fs::write(path, serde_json::to_string_pretty(&export)?)?;
+    Ok(())
+}
+
+fn write_gpl(path: &Path, clusters: &[ColorCluster]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut contents = String::new();
+    writeln!(contents, "GIMP Palette")?;
+    writeln!(contents, "Name: video-color-kmeans")?;
+    writeln!(contents, "Columns: {}", clusters.len().max(1))?;
+    writeln!(contents, "#")?;
+    for (idx, cluster) in clusters.iter().enumerate() {
+        let Srgb { red, green, blue, .. } = cluster.centroid.0;
+        writeln!(contents, "{:3} {:3} {:3} Cluster {}", red, green, blue, idx + 1)?;
+    }
+
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+fn write_css(path: &Path, clusters: &[ColorCluster]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut contents = String::from(":root {\n");
+    for (idx, cluster) in clusters.iter().enumerate() {
+        let Srgb { red, green, blue, .. } = cluster.centroid.0;
+        writeln!(
+            contents,
+            "  --palette-{}: #{:02X}{:02X}{:02X};",
+            idx + 1,
+            red,
+            green,
+            blue
+        )?;
+    }
+    contents.push_str("}\n");
+
+    fs::write(path, contents)?;
+    Ok(())
+}
+
+fn write_png(path: &Path, clusters: &[ColorCluster]) -> Result<(), Box<dyn std::error::Error>> {
+    let entries: Vec<(Color, u64)> = clusters
+        .iter()
+        .map(|cluster| {
+            let total_count: u64 = cluster.assignments.iter().map(|(_, count)| *count as u64).sum();
+            (cluster.centroid, total_count.max(1))
+        })
+        .collect();
+
+    render_color_strip(&entries, 200).save(path)?;
+    Ok(())
+}
+
+/// Write the palette and clusters out in `format`. `Terminal` is a no-op here; the terminal
+/// printers in `main.rs` handle that case directly.
+pub fn write_palette(
+    format: OutputFormat,
+    output_path: &Path,
+    color_ranking: &[(Color, usize)],
+    clusters: &[ColorCluster],
+    top_n: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        OutputFormat::Terminal => Ok(()),
+        OutputFormat::Json => write_json(output_path, color_ranking, clusters, top_n),
+        OutputFormat::Gpl => write_gpl(output_path, clusters),
+        OutputFormat::Css => write_css(output_path, clusters),
+        OutputFormat::Png => write_png(output_path, clusters),
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use palette::Srgb;
+
+use crate::color::{create_color_clusters, extract_colors_from_frame, Color, ColorCluster, ColorSpace};
+use crate::image_output::render_color_strip;
+use crate::video::VideoFrameIterator;
+
+/// How many bins each color channel is quantized into when building the per-frame histogram
+/// used for scene-cut detection. 8 bins/channel (512 total) is coarse enough to be robust to
+/// noise and resize artifacts while still separating visually distinct scenes.
+const BINS_PER_CHANNEL: usize = 8;
+const NUM_BINS: usize = BINS_PER_CHANNEL * BINS_PER_CHANNEL * BINS_PER_CHANNEL;
+
+/// One contiguous run of sampled frames between two detected scene cuts.
+pub struct Scene {
+    /// Index of the first sampled frame in this scene, in sampled-frame (not source-video) units.
+    pub start_frame: u64,
+    /// Index one past the last sampled frame in this scene.
+    pub end_frame: u64,
+    pub color_counts: HashMap<Color, usize>,
+}
+
+/// A scene together with the color clusters computed from just its own frames.
+pub struct ScenePalette {
+    pub start_frame: u64,
+    pub end_frame: u64,
+    pub clusters: Vec<ColorCluster>,
+}
+
+/// A per-frame color histogram together with the number of samples it was built from. The
+/// sample count lets `histogram_intersection_distance` tell "no colors survived the filter"
+/// apart from "colors survived but share nothing in common" — both produce a zero
+/// intersection, but only the latter is actually maximally different.
+struct FrameHistogram {
+    bins: [f64; NUM_BINS],
+    sample_count: usize,
+}
+
+fn frame_histogram(colors: &[Color]) -> FrameHistogram {
+    let mut bins = [0f64; NUM_BINS];
+    for color in colors {
+        let Srgb { red, green, blue, .. } = color.0;
+        let r_bin = (red as usize * BINS_PER_CHANNEL) / 256;
+        let g_bin = (green as usize * BINS_PER_CHANNEL) / 256;
+        let b_bin = (blue as usize * BINS_PER_CHANNEL) / 256;
+        let bin = (r_bin * BINS_PER_CHANNEL + g_bin) * BINS_PER_CHANNEL + b_bin;
+        bins[bin] += 1.0;
+    }
+
+    let sample_count = colors.len();
+    if sample_count > 0 {
+        let total = sample_count as f64;
+        for bin in bins.iter_mut() {
+            *bin /= total;
+        }
+    }
+    FrameHistogram { bins, sample_count }
+}
+
+/// The normalized histogram intersection distance between two frames: `0.0` for identical
+/// color distributions, approaching `1.0` as they share nothing in common. Two frames that
+/// both had every color filtered out (pure black, letterboxing, a fade) are treated as the
+/// same scene rather than maximally different — without this, a black/fade/dark run gets
+/// shredded into one spurious scene per frame instead of staying a single segment.
+fn histogram_intersection_distance(a: &FrameHistogram, b: &FrameHistogram) -> f64 {
+    if a.sample_count == 0 && b.sample_count == 0 {
+        return 0.0;
+    }
+    let intersection: f64 = a.bins.iter().zip(b.bins.iter()).map(|(x, y)| x.min(*y)).sum();
+    1.0 - intersection
+}
+
+/// Segment `frame_iterator` into scenes by tracking a running color histogram between
+/// consecutive sampled frames and flagging a cut whenever the histogram intersection distance
+/// exceeds `scene_threshold`.
+pub fn detect_scenes(
+    frame_iterator: VideoFrameIterator,
+    saturation_threshold: f32,
+    luminance_threshold: f32,
+    scene_threshold: f64,
+) -> Result<Vec<Scene>, Box<dyn std::error::Error>> {
+    let mut scenes = Vec::new();
+    let mut current = Scene {
+        start_frame: 0,
+        end_frame: 0,
+        color_counts: HashMap::new(),
+    };
+    let mut previous_histogram: Option<FrameHistogram> = None;
+
+    for (sample_index, frame) in frame_iterator.enumerate() {
+        let sample_index = sample_index as u64;
+        let colors = extract_colors_from_frame(&frame, saturation_threshold, luminance_threshold);
+        let histogram = frame_histogram(&colors);
+
+        if let Some(previous) = &previous_histogram {
+            if histogram_intersection_distance(previous, &histogram) > scene_threshold {
+                current.end_frame = sample_index;
+                let finished = std::mem::replace(
+                    &mut current,
+                    Scene {
+                        start_frame: sample_index,
+                        end_frame: sample_index,
+                        color_counts: HashMap::new(),
+                    },
+                );
+                scenes.push(finished);
+            }
+        }
+
+        for color in colors {
+            *current.color_counts.entry(color).or_insert(0) += 1;
+        }
+        current.end_frame = sample_index + 1;
+        previous_histogram = Some(histogram);
+    }
+
+    // Push the trailing scene whenever it actually contains sampled frames, matching how
+    // interior scenes are pushed above regardless of whether any colors survived the filter —
+    // a video ending on a fade-to-black shouldn't have its last segment silently dropped.
+    if current.end_frame > current.start_frame {
+        scenes.push(current);
+    }
+
+    Ok(scenes)
+}
+
+/// Run `create_color_clusters` independently on each scene's accumulated colors.
+pub fn build_scene_palettes(
+    scenes: &[Scene],
+    num_clusters: usize,
+    color_space: ColorSpace,
+) -> Vec<ScenePalette> {
+    scenes
+        .iter()
+        .map(|scene| {
+            let ranking: Vec<(Color, usize)> = scene.color_counts.iter().map(|(c, n)| (*c, *n)).collect();
+            let num_clusters = num_clusters.min(ranking.len().max(1));
+            let clusters = create_color_clusters(&ranking, num_clusters, color_space);
+
+            ScenePalette {
+                start_frame: scene.start_frame,
+                end_frame: scene.end_frame,
+                clusters,
+            }
+        })
+        .collect()
+}
+
+/// The centroid of a scene's most heavily-populated cluster, used as that scene's single
+/// representative color in the barcode.
+fn dominant_centroid(palette: &ScenePalette) -> Color {
+    palette
+        .clusters
+        .iter()
+        .max_by_key(|cluster| cluster.assignments.iter().map(|(_, count)| *count as u64).sum::<u64>())
+        .map(|cluster| cluster.centroid)
+        .unwrap_or(Color(Srgb::new(0, 0, 0)))
+}
+
+/// Render and write the "movie barcode": one vertical stripe per scene, sized proportionally
+/// to the scene's length and colored by its dominant cluster centroid.
+pub fn write_scene_barcode(
+    palettes: &[ScenePalette],
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entries: Vec<(Color, u64)> = palettes
+        .iter()
+        .map(|palette| {
+            let weight = palette.end_frame.saturating_sub(palette.start_frame).max(1);
+            (dominant_centroid(palette), weight)
+        })
+        .collect();
+
+    let image = render_color_strip(&entries, 200);
+    image.save(path)?;
+    Ok(())
+}
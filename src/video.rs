@@ -1,14 +1,324 @@
+use std::io::Read;
 use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
 use std::time::Duration;
 
+use clap::ValueEnum;
 use opencv::prelude::*;
-use opencv::videoio;
+use opencv::{core, imgproc, videoio};
 
-/// A struct representing an iterator over video frames.
+/// A source of decoded video frames, abstracting over the backend used to demux and decode
+/// the underlying container.
+///
+/// Frames returned by a `VideoSource` are always already resized to a common height and
+/// converted to RGB, so callers never need to know which backend produced them.
+pub trait VideoSource {
+    /// Read the frame at `frame_index`, seeking first if the source isn't already positioned
+    /// there. Returns `None` once the source is exhausted, which can happen earlier than
+    /// `frame_count` suggests for variable-framerate files where the container's reported
+    /// frame count is only an estimate.
+    fn read_frame(&mut self, frame_index: u64) -> Option<Mat>;
+
+    /// The source's best estimate of its total frame count. Treat this as a hint for sizing
+    /// progress bars, not a guarantee that `frame_count` frames are actually decodable.
+    fn frame_count(&self) -> u64;
+}
+
+/// Resize `frame` to `height`, preserving aspect ratio. Frames within a single file can vary
+/// in resolution, so this is computed per-frame rather than assumed once up front.
+fn resize_to_height(frame: &Mat, height: i32) -> Mat {
+    let aspect_ratio = frame.cols() as f64 / frame.rows() as f64;
+    let new_width = (height as f64 * aspect_ratio).round() as i32;
+
+    let mut resized = Mat::default();
+    imgproc::resize(
+        frame,
+        &mut resized,
+        core::Size::new(new_width, height),
+        0.,
+        0.,
+        imgproc::INTER_AREA,
+    )
+    .unwrap();
+    resized
+}
+
+/// A `VideoSource` backed by OpenCV's `VideoCapture`.
+pub struct OpenCvVideoSource {
+    capture: videoio::VideoCapture,
+    frame_count: u64,
+    fps: f64,
+    resize_height: i32,
+    next_sequential_frame: u64,
+}
+
+impl OpenCvVideoSource {
+    pub fn new(video_file: &Path, resize_height: i32) -> Result<Self, Box<dyn std::error::Error>> {
+        let capture =
+            videoio::VideoCapture::from_file(video_file.to_str().unwrap(), videoio::CAP_ANY)?;
+        let fps = capture.get(videoio::CAP_PROP_FPS)?;
+        let frame_count = capture.get(videoio::CAP_PROP_FRAME_COUNT)? as u64;
+
+        Ok(Self {
+            capture,
+            frame_count,
+            fps,
+            resize_height,
+            next_sequential_frame: 0,
+        })
+    }
+
+    /// The container's nominal frames-per-second. For variable-framerate clips this is only a
+    /// nominal value, useful for converting timestamps to an approximate frame index, not an
+    /// exact one.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+}
+
+impl VideoSource for OpenCvVideoSource {
+    fn read_frame(&mut self, frame_index: u64) -> Option<Mat> {
+        // Seeking via CAP_PROP_POS_FRAMES is a good deal slower than just reading the next
+        // frame, and frame-step sampling still reads sequentially within a GOP, so only seek
+        // when we're not already positioned where we need to be.
+        if frame_index != self.next_sequential_frame {
+            self.capture
+                .set(videoio::CAP_PROP_POS_FRAMES, frame_index as f64)
+                .ok()?;
+        }
+
+        let mut frame = Mat::default();
+        if !self.capture.read(&mut frame).ok()? || frame.empty() {
+            return None;
+        }
+        self.next_sequential_frame = frame_index + 1;
+
+        let resized = resize_to_height(&frame, self.resize_height);
+        let mut rgb_frame = Mat::default();
+        imgproc::cvt_color(&resized, &mut rgb_frame, imgproc::COLOR_BGR2RGB, 3).unwrap();
+        Some(rgb_frame)
+    }
+
+    fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+}
+
+/// A `VideoSource` that shells out to `ffmpeg` and reads decoded frames off a pipe, for users
+/// who don't have an OpenCV build but do have `ffmpeg` on their `PATH`. Scaling and frame-rate
+/// thinning are both pushed into the ffmpeg filter graph, so the heaviest work happens outside
+/// this process and frames arrive already resized and in RGB.
+pub struct FfmpegVideoSource {
+    child: Child,
+    stdout: ChildStdout,
+    frame_width: u32,
+    frame_height: u32,
+    frame_count: u64,
+    fps: f64,
+}
+
+impl FfmpegVideoSource {
+    pub fn new(
+        video_file: &Path,
+        resize_height: i32,
+        frame_step: u64,
+        start_timestamp: Option<Duration>,
+        end_timestamp: Option<Duration>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let probe = ffprobe(video_file)?;
+        let sample_fps = (probe.fps / frame_step.max(1) as f64).max(1e-3);
+
+        // We need to know the exact output frame size to read fixed-size chunks off the pipe,
+        // so compute the scaled width ourselves instead of trusting ffmpeg's `scale=-1:height`
+        // to report it back to us. ffmpeg's scaler rounds to an even width for yuv-family pixel
+        // formats; rgb24 doesn't require it, but rounding down keeps us compatible either way.
+        let scaled_width =
+            ((resize_height as f64 / probe.height as f64) * probe.width as f64).round() as u32;
+        let scaled_width = scaled_width - (scaled_width % 2);
+
+        let mut command = Command::new("ffmpeg");
+
+        // `-ss` before `-i` is input seeking: ffmpeg seeks to the nearest keyframe at or before
+        // the timestamp before decoding anything, so this is cheap even for a window near the
+        // end of a long file. It must be given in source time, before the `fps` filter thins
+        // the stream down to the sampled rate.
+        if let Some(start) = start_timestamp {
+            command.arg("-ss").arg(format!("{:.3}", start.as_secs_f64()));
+        }
+
+        command.arg("-i").arg(video_file);
+
+        if let Some(end) = end_timestamp {
+            let start_secs = start_timestamp.map_or(0.0, |ts| ts.as_secs_f64());
+            let duration = (end.as_secs_f64() - start_secs).max(0.0);
+            command.arg("-t").arg(format!("{:.3}", duration));
+        }
+
+        let mut child = command
+            .args([
+                "-vf",
+                &format!("scale={}:{},fps={}", scaled_width, resize_height, sample_fps),
+                "-f",
+                "rawvideo",
+                "-pix_fmt",
+                "rgb24",
+                "-",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or("failed to capture ffmpeg's stdout")?;
+
+        Ok(Self {
+            child,
+            stdout,
+            frame_width: scaled_width,
+            frame_height: resize_height as u32,
+            frame_count: probe.frame_count,
+            fps: probe.fps,
+        })
+    }
+
+    /// The container's nominal frames-per-second, as reported by `ffprobe`.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+}
+
+/// Build a 3-channel (`CV_8UC3`) Mat from a raw `rgb24` buffer (3 interleaved u8 channels per
+/// pixel, row-major). `new_rows_cols_with_data` infers the Mat's element type from the slice
+/// it's given, so handing it a `&[u8]` directly would build a single-channel (`CV_8UC1`) Mat
+/// the wrong size for the data; wrapping it as `Vec3b` (OpenCV's 3-channel byte pixel type)
+/// first makes the element type match what `extract_colors_from_frame` expects.
+fn rgb24_to_mat(buf: &[u8], width: u32, height: u32) -> Option<Mat> {
+    if buf.len() != (width as usize) * (height as usize) * 3 {
+        return None;
+    }
+
+    let pixels: &[core::Vec3b] =
+        unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const core::Vec3b, buf.len() / 3) };
+    let frame =
+        unsafe { Mat::new_rows_cols_with_data(height as i32, width as i32, pixels).ok()? };
+    // `new_rows_cols_with_data` borrows `pixels`/`buf`, which are about to be dropped, so clone
+    // into a Mat that owns its data before returning it.
+    frame.try_clone().ok()
+}
+
+impl VideoSource for FfmpegVideoSource {
+    fn read_frame(&mut self, _frame_index: u64) -> Option<Mat> {
+        // ffmpeg already thinned the stream down to the sampled frame rate via the `fps`
+        // filter, and already seeked (if requested) via `-ss`, so every frame read off the
+        // pipe is one we want; there's nothing left to do with `frame_index`.
+        let frame_len = (self.frame_width as usize) * (self.frame_height as usize) * 3;
+        let mut buf = vec![0u8; frame_len];
+        self.stdout.read_exact(&mut buf).ok()?;
+
+        rgb24_to_mat(&buf, self.frame_width, self.frame_height)
+    }
+
+    fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+}
+
+impl Drop for FfmpegVideoSource {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct VideoProbe {
+    width: u32,
+    height: u32,
+    fps: f64,
+    frame_count: u64,
+}
+
+/// Probe a video file's resolution, frame rate, and frame count with `ffprobe`, without
+/// depending on OpenCV to do it.
+fn ffprobe(video_file: &Path) -> Result<VideoProbe, Box<dyn std::error::Error>> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_entries",
+            "stream=width,height,r_frame_rate,nb_frames",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(video_file)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "ffprobe exited with an error: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut lines = stdout.lines();
+
+    let width = lines.next().ok_or("ffprobe output is missing width")?.trim().parse()?;
+    let height = lines
+        .next()
+        .ok_or("ffprobe output is missing height")?
+        .trim()
+        .parse()?;
+
+    let frame_rate = lines.next().ok_or("ffprobe output is missing r_frame_rate")?;
+    let mut frame_rate_parts = frame_rate.trim().split('/');
+    let numerator: f64 = frame_rate_parts
+        .next()
+        .ok_or("r_frame_rate is missing a numerator")?
+        .parse()?;
+    let denominator: f64 = match frame_rate_parts.next() {
+        Some(d) => d.parse()?,
+        None => 1.0,
+    };
+    let fps = numerator / denominator;
+
+    // Some containers (notably variable-framerate clips) don't store a total frame count, in
+    // which case ffprobe reports "N/A"; treat the count as unbounded and let the iterator stop
+    // whenever the pipe runs dry instead.
+    let frame_count = lines
+        .next()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(u64::MAX);
+
+    Ok(VideoProbe {
+        width,
+        height,
+        fps,
+        frame_count,
+    })
+}
+
+/// Which backend to use for decoding video frames.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Decoder {
+    /// Decode with OpenCV's `VideoCapture`, seeking directly to sampled frames.
+    Opencv,
+    /// Shell out to `ffmpeg` and read decoded, pre-scaled, pre-thinned frames off a pipe.
+    Ffmpeg,
+}
+
+/// A struct representing an iterator over video frames, sampling every `frame_step`th frame
+/// between `start_frame` and `end_frame` from a pluggable `VideoSource`.
 pub struct VideoFrameIterator {
-    pub capture: videoio::VideoCapture,
+    source: Box<dyn VideoSource>,
+    pub current_frame: u64,
     pub end_frame: u64,
-    pub frame_number: u64,
+    pub frame_step: u64,
 }
 
 impl VideoFrameIterator {
@@ -19,6 +329,9 @@ impl VideoFrameIterator {
     /// * `video_file` - A path to the video file.
     /// * `start_timestamp` - An optional duration representing the starting timestamp of the video.
     /// * `end_timestamp` - An optional duration representing the ending timestamp of the video.
+    /// * `frame_step` - Only every `frame_step`th frame is yielded; `1` samples every frame.
+    /// * `resize_height` - The common height every yielded frame is resized to.
+    /// * `decoder` - Which backend to decode frames with.
     ///
     /// # Returns
     ///
@@ -27,47 +340,68 @@ impl VideoFrameIterator {
         video_file: &Path,
         start_timestamp: Option<Duration>,
         end_timestamp: Option<Duration>,
+        frame_step: u64,
+        resize_height: i32,
+        decoder: Decoder,
     ) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut capture =
-            videoio::VideoCapture::from_file(video_file.to_str().unwrap(), videoio::CAP_ANY)?;
-        let fps = capture.get(videoio::CAP_PROP_FPS)?;
-        let total_frames = capture.get(videoio::CAP_PROP_FRAME_COUNT)? as u64;
+        let frame_step = frame_step.max(1);
+        let (source, fps, total_frames): (Box<dyn VideoSource>, f64, u64) = match decoder {
+            Decoder::Opencv => {
+                let source = OpenCvVideoSource::new(video_file, resize_height)?;
+                let fps = source.fps();
+                let total_frames = source.frame_count();
+                (Box::new(source), fps, total_frames)
+            }
+            Decoder::Ffmpeg => {
+                let source = FfmpegVideoSource::new(
+                    video_file,
+                    resize_height,
+                    frame_step,
+                    start_timestamp,
+                    end_timestamp,
+                )?;
+                let fps = source.fps();
+                let total_frames = source.frame_count();
+                (Box::new(source), fps, total_frames)
+            }
+        };
 
         let start_frame = start_timestamp.map_or(0, |ts| (ts.as_secs_f64() * fps).round() as u64);
-        let end_frame =
-            end_timestamp.map_or(total_frames, |ts| (ts.as_secs_f64() * fps).round() as u64);
-
-        if start_frame > 0 {
-            capture.set(videoio::CAP_PROP_POS_FRAMES, start_frame as f64)?;
-        }
+        let end_frame = end_timestamp
+            .map_or(total_frames, |ts| (ts.as_secs_f64() * fps).round() as u64)
+            .min(total_frames);
 
         Ok(Self {
-            capture,
+            source,
+            current_frame: start_frame,
             end_frame,
-            frame_number: start_frame,
+            frame_step,
         })
     }
+
+    /// An estimate of how many frames remain to be sampled, for sizing progress bars. This is
+    /// only an estimate: `read_frame` may run out of frames sooner on variable-framerate clips
+    /// whose reported frame count doesn't match what's actually decodable.
+    pub fn estimated_remaining(&self) -> u64 {
+        self.end_frame.saturating_sub(self.current_frame) / self.frame_step
+    }
 }
 
 impl Iterator for VideoFrameIterator {
     type Item = Mat;
 
-    /// Return the next frame in the video.
+    /// Return the next sampled frame in the video.
     ///
     /// # Returns
     ///
     /// An `Option` containing the next frame as a `Mat` object, or `None` if there are no more frames.
     fn next(&mut self) -> Option<Self::Item> {
-        if self.frame_number >= self.end_frame {
-            return None;
-        }
-
-        let mut frame = Mat::default();
-        if !self.capture.read(&mut frame).unwrap() {
+        if self.current_frame >= self.end_frame {
             return None;
         }
 
-        self.frame_number += 1;
+        let frame = self.source.read_frame(self.current_frame)?;
+        self.current_frame += self.frame_step;
         Some(frame)
     }
 }
@@ -85,7 +419,7 @@ mod tests {
     #[test]
     fn test_video_frame_iterator_creation() {
         let video_path = get_video_path();
-        let iterator = VideoFrameIterator::new(&video_path, None, None);
+        let iterator = VideoFrameIterator::new(&video_path, None, None, 1, 720, Decoder::Opencv);
 
         assert!(iterator.is_ok());
     }
@@ -93,7 +427,7 @@ mod tests {
     #[test]
     fn test_video_frame_iterator_iteration() {
         let video_path = get_video_path();
-        let iterator = VideoFrameIterator::new(&video_path, None, None).unwrap();
+        let iterator = VideoFrameIterator::new(&video_path, None, None, 1, 720, Decoder::Opencv).unwrap();
         let mut frame_count = 0;
 
         for _frame in iterator {
@@ -108,7 +442,15 @@ mod tests {
         let video_path = get_video_path();
         let start_timestamp = Duration::from_secs(2);
         let end_timestamp = Duration::from_secs(4);
-        let iterator = VideoFrameIterator::new(&video_path, Some(start_timestamp), Some(end_timestamp)).unwrap();
+        let iterator = VideoFrameIterator::new(
+            &video_path,
+            Some(start_timestamp),
+            Some(end_timestamp),
+            1,
+            720,
+            Decoder::Opencv,
+        )
+        .unwrap();
 
         let mut frame_count = 0;
         let mut last_frame = None;
@@ -126,4 +468,51 @@ mod tests {
             panic!("No frames found");
         }
     }
+
+    #[test]
+    fn test_video_frame_iterator_frame_step() {
+        let video_path = get_video_path();
+        let iterator = VideoFrameIterator::new(&video_path, None, None, 5, 720, Decoder::Opencv).unwrap();
+        let mut frame_count = 0;
+
+        for _frame in iterator {
+            frame_count += 1;
+        }
+
+        assert!(frame_count > 0);
+    }
+
+    #[test]
+    fn test_rgb24_to_mat_rejects_mismatched_buffer_length() {
+        let buf = vec![0u8; 10];
+        assert!(rgb24_to_mat(&buf, 2, 2).is_none());
+    }
+
+    #[test]
+    fn test_rgb24_to_mat_round_trips_through_extract_colors_from_frame() {
+        // 2x2 rgb24 buffer, row-major: a mid-saturation red, green, blue, and a color that
+        // should be dropped by the default whiteness filter.
+        let width = 2;
+        let height = 2;
+        #[rustfmt::skip]
+        let buf: Vec<u8> = vec![
+            200, 40, 40,    40, 200, 40,
+            40, 40, 200,    255, 255, 255,
+        ];
+
+        let frame = rgb24_to_mat(&buf, width, height).expect("buffer is the right length");
+        assert_eq!(frame.size().unwrap(), Size::new(width as i32, height as i32));
+
+        let colors = crate::color::extract_colors_from_frame(&frame, 0.0, 0.0);
+        assert_eq!(colors.len(), 3);
+        assert!(colors
+            .iter()
+            .any(|c| c.0.red == 200 && c.0.green == 40 && c.0.blue == 40));
+        assert!(colors
+            .iter()
+            .any(|c| c.0.red == 40 && c.0.green == 200 && c.0.blue == 40));
+        assert!(colors
+            .iter()
+            .any(|c| c.0.red == 40 && c.0.green == 40 && c.0.blue == 200));
+    }
 }